@@ -0,0 +1,138 @@
+use std::io::Write;
+
+use flate2::{write::DeflateEncoder, write::GzEncoder, Compression};
+
+use crate::HttpResponse;
+
+/// Bodies smaller than this aren't worth the CPU cost of compressing.
+const MIN_COMPRESSIBLE_SIZE: usize = 256;
+
+/// Content-Types that are never compressed: formats that are already
+/// compressed, plus the `application/octet-stream` fallback for unrecognized
+/// files (which covers archives such as `.gz`).
+const ALREADY_COMPRESSED_TYPES: &[&str] = &[
+    "image/png",
+    "image/jpeg",
+    "image/gif",
+    "application/octet-stream",
+];
+
+/// Compresses `response`'s body when it isn't a `Content-Range` partial
+/// response, the client's `Accept-Encoding` header lists a supported
+/// encoding, the body is large enough to be worth it, and its `Content-Type`
+/// isn't already compressed. Updates `Content-Length`, `Content-Encoding`,
+/// and `Vary` accordingly; otherwise returns `response` untouched.
+pub(crate) fn negotiate(mut response: HttpResponse, accept_encoding: Option<&str>) -> HttpResponse {
+    // A 206's Content-Range describes offsets into the uncompressed body;
+    // compressing it would leave the range header lying about what's on
+    // the wire, so partial content is never a compression candidate.
+    if response.headers.contains_key("Content-Range") {
+        return response;
+    }
+
+    if response.body().len() < MIN_COMPRESSIBLE_SIZE {
+        return response;
+    }
+
+    let already_compressed = response
+        .headers
+        .get("Content-Type")
+        .is_some_and(|content_type| ALREADY_COMPRESSED_TYPES.contains(&content_type.as_str()));
+    if already_compressed {
+        return response;
+    }
+
+    let Some(accept_encoding) = accept_encoding else {
+        return response;
+    };
+    let encodings = accept_encoding.split(',').map(|e| e.trim());
+
+    let compressed = if encodings.clone().any(|e| e == "gzip") {
+        gzip(response.body()).map(|body| (body, "gzip"))
+    } else if encodings.clone().any(|e| e == "deflate") {
+        deflate(response.body()).map(|body| (body, "deflate"))
+    } else {
+        None
+    };
+
+    let Some((body, encoding)) = compressed else {
+        return response;
+    };
+
+    response.headers.insert(
+        "Content-Length".to_string(),
+        body.len().to_string(),
+    );
+    response
+        .headers
+        .insert("Content-Encoding".to_string(), encoding.to_string());
+    response
+        .headers
+        .insert("Vary".to_string(), "Accept-Encoding".to_string());
+    response.set_body(body);
+    response
+}
+
+fn gzip(data: &[u8]) -> Option<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data).ok()?;
+    encoder.finish().ok()
+}
+
+fn deflate(data: &[u8]) -> Option<Vec<u8>> {
+    let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data).ok()?;
+    encoder.finish().ok()
+}
+
+#[cfg(test)]
+mod negotiate_tests {
+    use super::*;
+
+    fn body(len: usize) -> Vec<u8> {
+        vec![b'a'; len]
+    }
+
+    #[test]
+    fn compresses_a_large_body_when_gzip_is_accepted() {
+        let response = HttpResponse::new(body(MIN_COMPRESSIBLE_SIZE), "HTTP/1.1".to_string(), 200);
+        let response = negotiate(response, Some("gzip, deflate"));
+        assert_eq!(response.headers.get("Content-Encoding").unwrap(), "gzip");
+        assert!(response.body().len() < MIN_COMPRESSIBLE_SIZE);
+    }
+
+    #[test]
+    fn leaves_small_bodies_uncompressed() {
+        let response = HttpResponse::new(body(MIN_COMPRESSIBLE_SIZE - 1), "HTTP/1.1".to_string(), 200);
+        let response = negotiate(response, Some("gzip"));
+        assert!(!response.headers.contains_key("Content-Encoding"));
+    }
+
+    #[test]
+    fn leaves_response_uncompressed_without_a_matching_encoding() {
+        let response = HttpResponse::new(body(MIN_COMPRESSIBLE_SIZE), "HTTP/1.1".to_string(), 200);
+        let response = negotiate(response, Some("br"));
+        assert!(!response.headers.contains_key("Content-Encoding"));
+    }
+
+    #[test]
+    fn leaves_already_compressed_content_types_alone() {
+        let mut response = HttpResponse::new(body(MIN_COMPRESSIBLE_SIZE), "HTTP/1.1".to_string(), 200);
+        response
+            .headers
+            .insert("Content-Type".to_string(), "image/png".to_string());
+        let response = negotiate(response, Some("gzip"));
+        assert!(!response.headers.contains_key("Content-Encoding"));
+    }
+
+    #[test]
+    fn never_compresses_a_partial_content_response() {
+        let mut response = HttpResponse::new(body(MIN_COMPRESSIBLE_SIZE), "HTTP/1.1".to_string(), 206);
+        response
+            .headers
+            .insert("Content-Range".to_string(), "bytes 0-255/1024".to_string());
+        let response = negotiate(response, Some("gzip"));
+        assert!(!response.headers.contains_key("Content-Encoding"));
+        assert_eq!(response.body().len(), MIN_COMPRESSIBLE_SIZE);
+    }
+}
@@ -0,0 +1,95 @@
+use std::{fs, path::PathBuf};
+
+use anyhow::Context;
+use serde::Deserialize;
+
+/// Declares how incoming paths are routed before they ever reach the
+/// built-in router: each `[[mount]]` entry either serves a directory or
+/// reverse-proxies to an upstream origin.
+#[derive(Debug, Deserialize)]
+pub(crate) struct Config {
+    #[serde(rename = "mount", default)]
+    mounts: Vec<Mount>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub(crate) enum Mount {
+    Static {
+        prefix: String,
+        directory: PathBuf,
+    },
+    Proxy {
+        prefix: String,
+        upstream: String,
+    },
+}
+
+impl Mount {
+    fn prefix(&self) -> &str {
+        match self {
+            Mount::Static { prefix, .. } => prefix,
+            Mount::Proxy { prefix, .. } => prefix,
+        }
+    }
+}
+
+impl Config {
+    pub(crate) fn load(path: &str) -> anyhow::Result<Self> {
+        let contents =
+            fs::read_to_string(path).with_context(|| format!("CTX: read config file {}", path))?;
+        toml::from_str(&contents).with_context(|| format!("CTX: parse config file {}", path))
+    }
+
+    /// Returns the longest-prefix mount matching `path`, if any.
+    pub(crate) fn find_mount(&self, path: &str) -> Option<&Mount> {
+        self.mounts
+            .iter()
+            .filter(|mount| path.starts_with(mount.prefix()))
+            .max_by_key(|mount| mount.prefix().len())
+    }
+}
+
+#[cfg(test)]
+mod find_mount_tests {
+    use super::{Config, Mount};
+
+    fn config(mounts: Vec<Mount>) -> Config {
+        Config { mounts }
+    }
+
+    #[test]
+    fn no_mounts_match_nothing() {
+        let config = config(Vec::new());
+        assert!(config.find_mount("/static/app.css").is_none());
+    }
+
+    #[test]
+    fn matches_a_single_prefix() {
+        let config = config(vec![Mount::Static {
+            prefix: "/static".to_string(),
+            directory: "public".into(),
+        }]);
+        assert!(config.find_mount("/static/app.css").is_some());
+        assert!(config.find_mount("/other").is_none());
+    }
+
+    #[test]
+    fn prefers_the_longest_matching_prefix() {
+        let config = config(vec![
+            Mount::Static {
+                prefix: "/static".to_string(),
+                directory: "public".into(),
+            },
+            Mount::Proxy {
+                prefix: "/static/api".to_string(),
+                upstream: "127.0.0.1:9000".to_string(),
+            },
+        ]);
+
+        match config.find_mount("/static/api/users").unwrap() {
+            Mount::Proxy { prefix, .. } => assert_eq!(prefix, "/static/api"),
+            Mount::Static { .. } => panic!("expected the longer /static/api prefix to win"),
+        }
+    }
+}
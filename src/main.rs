@@ -1,106 +1,427 @@
+mod compression;
+mod config;
+mod proxy;
+mod router;
+
 use anyhow::{Context, Ok};
 use clap::Parser;
 use core::fmt;
-use std::{collections::HashMap, fs, path::Path, sync::Arc};
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{Read as _, Seek, SeekFrom},
+    path::Path,
+    sync::Arc,
+    time::Duration,
+};
 use tokio::{
     io::{AsyncReadExt, AsyncWriteExt},
     net::{TcpListener, TcpStream},
+    time::timeout,
 };
 
+use config::{Config, Mount};
+use router::{Method, Router};
+
+/// How long a connection may sit idle (no bytes of a new request) before
+/// we give up on it and let the socket drop.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Args {
     #[arg(long)]
     directory: Option<String>,
+
+    /// Path to a TOML config declaring static/proxy mounts.
+    #[arg(long)]
+    config: Option<String>,
 }
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     let arg = Arc::new(Args::parse());
+    let router = Arc::new(build_router());
+    let config = Arc::new(match arg.config.as_deref() {
+        Some(path) => Some(Config::load(path).context("CTX: load config file")?),
+        None => None,
+    });
     let listener = TcpListener::bind("127.0.0.1:4221").await?;
     loop {
         // The second item contains the IP and port of the new connection.
         let (mut socket, _) = listener.accept().await?;
         let cloned_arg = arg.clone();
+        let cloned_router = router.clone();
+        let cloned_config = config.clone();
 
         tokio::spawn(async move {
-            if let Err(e) = process(&mut socket, cloned_arg).await {
+            if let Err(e) = process(&mut socket, cloned_arg, cloned_router, cloned_config).await {
                 eprintln!("Error handling request {:?}", e)
             };
         });
     }
 }
 
-async fn process(stream: &mut TcpStream, arg: Arc<Args>) -> anyhow::Result<()> {
-    let mut buf = [0u8; 1024];
-    stream
-        .read(&mut buf)
-        .await
-        .context("CTX: handle connection read buffer")?;
-
-    let request = HttpRequest::from_byte_array(&buf);
-
-    let response = if request.verb == "GET" && request.path == "/" {
-        HttpResponse::new("".to_string(), request.protocol, 200)
-    } else if request.verb == "GET" && request.path.starts_with("/echo/") {
-        let echo_content = request
-            .path
-            .split_once("/echo/")
-            .expect("Echo should contain content")
-            .1;
-        let response = HttpResponse::new(echo_content.to_string(), request.protocol, 200);
-        response.prepare_plain_text_headers()
-    } else if request.verb == "GET" && request.path == "/user-agent" {
-        let response = HttpResponse::new(
-            request.headers.get("User-Agent").unwrap().to_string(),
-            request.protocol,
-            200,
-        );
-        response.prepare_plain_text_headers()
-    } else if request.verb == "GET" && request.path.starts_with("/files/") {
-        let filename = request.path.split_once("/files").unwrap().1;
-        dbg!("file name {}", filename);
-        let path_str = format!("{}/{}", arg.directory.to_owned().unwrap(), filename);
-        let path = Path::new(&path_str);
-
-        if path.exists() {
-            let body = fs::read_to_string(path).expect("Read file always sucuess");
-            let response = HttpResponse::new(body, request.protocol, 200);
-            response.prepare_octet_stream_headers()
+async fn process(
+    stream: &mut TcpStream,
+    arg: Arc<Args>,
+    router: Arc<Router<Arc<Args>>>,
+    config: Arc<Option<Config>>,
+) -> anyhow::Result<()> {
+    // Bytes already read off `stream` that belong to a following, pipelined
+    // request; carried across iterations so they aren't dropped once the
+    // current request's body has been sliced off.
+    let mut leftover = Vec::new();
+    loop {
+        let request = match read_request(stream, &mut leftover)
+            .await
+            .context("CTX: read and parse request")?
+        {
+            Some(request) => request,
+            None => return Ok(()),
+        };
+
+        // HTTP/1.1 is persistent by default and opts out with `Connection: close`;
+        // HTTP/1.0 is the opposite, staying persistent only when the client asks
+        // for `Connection: keep-alive`.
+        let keep_alive = if request.protocol == "HTTP/1.0" {
+            request
+                .headers
+                .get("Connection")
+                .is_some_and(|value| value.eq_ignore_ascii_case("keep-alive"))
+        } else {
+            !request
+                .headers
+                .get("Connection")
+                .is_some_and(|value| value.eq_ignore_ascii_case("close"))
+        };
+        let connection_header = if keep_alive { "keep-alive" } else { "close" }.to_string();
+
+        let mount = config.as_ref().as_ref().and_then(|c| c.find_mount(&request.path));
+        match mount {
+            Some(Mount::Proxy { upstream, .. }) => {
+                proxy::forward(stream, &request, upstream, &connection_header).await?;
+            }
+            Some(Mount::Static { directory, prefix }) => {
+                let mut response = serve_static_mount(&request, directory, prefix);
+                response
+                    .headers
+                    .insert("Connection".to_string(), connection_header.clone());
+                let response = compression::negotiate(
+                    response,
+                    request.headers.get("Accept-Encoding").map(String::as_str),
+                );
+                stream.write_all(&response.to_bytes()).await?;
+            }
+            None => {
+                let mut response = router.dispatch(&request, &arg);
+                response
+                    .headers
+                    .insert("Connection".to_string(), connection_header.clone());
+                let response = compression::negotiate(
+                    response,
+                    request.headers.get("Accept-Encoding").map(String::as_str),
+                );
+                stream.write_all(&response.to_bytes()).await?;
+            }
+        }
+
+        if !keep_alive {
+            return Ok(());
+        }
+    }
+}
+
+/// Serves a file from a static mount's directory, honoring the mount's
+/// prefix the way `/files/:name` honors its own. Rejects any `..` segment in
+/// the relative path so a request can't escape `directory`.
+fn serve_static_mount(request: &HttpRequest, directory: &Path, prefix: &str) -> HttpResponse {
+    let relative = request
+        .path
+        .strip_prefix(prefix)
+        .unwrap_or(&request.path)
+        .trim_start_matches('/');
+
+    if relative.split('/').any(|segment| segment == "..") {
+        return HttpResponse::new(Vec::new(), request.protocol.clone(), 404);
+    }
+
+    let path = directory.join(relative);
+
+    if path.exists() {
+        let body = fs::read(&path).expect("Read file always sucuess");
+        let response = HttpResponse::new(body, request.protocol.clone(), 200);
+        response.prepare_file_headers(&path)
+    } else {
+        HttpResponse::new(Vec::new(), request.protocol.clone(), 404)
+    }
+}
+
+/// Registers the server's routes. Adding an endpoint is one `register` call
+/// rather than another branch in a dispatch chain.
+fn build_router() -> Router<Arc<Args>> {
+    let mut router = Router::new();
+
+    router.register(
+        Method::Get,
+        "/",
+        Box::new(|request, _params, _arg: &Arc<Args>| {
+            HttpResponse::new(Vec::new(), request.protocol.clone(), 200)
+        }),
+    );
+
+    router.register(
+        Method::Get,
+        "/echo/:text*",
+        Box::new(|request, params, _arg| {
+            let echo_content = params.get("text").cloned().unwrap_or_default();
+            let response =
+                HttpResponse::new(echo_content.into_bytes(), request.protocol.clone(), 200);
+            response.prepare_plain_text_headers()
+        }),
+    );
+
+    router.register(
+        Method::Get,
+        "/user-agent",
+        Box::new(|request, _params, _arg| {
+            let user_agent = request
+                .headers
+                .get("User-Agent")
+                .cloned()
+                .unwrap_or_default();
+            let response = HttpResponse::new(user_agent.into_bytes(), request.protocol.clone(), 200);
+            response.prepare_plain_text_headers()
+        }),
+    );
+
+    router.register(
+        Method::Get,
+        "/files/:name*",
+        Box::new(|request, params, arg| {
+            let filename = params.get("name").cloned().unwrap_or_default();
+            let path_str = format!("{}/{}", arg.directory.to_owned().unwrap(), filename);
+            let path = Path::new(&path_str);
+
+            if path.exists() {
+                if let Some(range) = request.headers.get("Range") {
+                    let total = fs::metadata(path).map(|meta| meta.len() as usize).unwrap_or(0);
+                    match parse_range(range, total) {
+                        Some((start, end)) => {
+                            let slice = read_range(path, start, end).expect("Read file always sucuess");
+                            let response =
+                                HttpResponse::new(slice, request.protocol.clone(), 206);
+                            response.prepare_range_headers(path, start, end, total)
+                        }
+                        None => {
+                            let response =
+                                HttpResponse::new(Vec::new(), request.protocol.clone(), 416);
+                            response.prepare_range_not_satisfiable_headers(total)
+                        }
+                    }
+                } else {
+                    let body = fs::read(path).expect("Read file always sucuess");
+                    let response = HttpResponse::new(body, request.protocol.clone(), 200);
+                    response.prepare_file_headers(path)
+                }
+            } else {
+                HttpResponse::new(Vec::new(), request.protocol.clone(), 404)
+            }
+        }),
+    );
+
+    router.register(
+        Method::Post,
+        "/files/:name*",
+        Box::new(|request, params, arg| {
+            let filename = params.get("name").cloned().unwrap_or_default();
+            let path_str = format!("{}/{}", arg.directory.to_owned().unwrap(), filename);
+            let path = Path::new(&path_str);
+
+            let _ = fs::write(path, &request.body);
+
+            HttpResponse::new(Vec::new(), request.protocol.clone(), 201)
+        }),
+    );
+
+    router
+}
+
+/// Reads a full HTTP/1.1 request off `stream`: accumulates bytes (starting
+/// from any `leftover` carried over from a previous, pipelined request)
+/// until the `\r\n\r\n` header terminator is found, then keeps reading until
+/// exactly `Content-Length` body bytes have arrived. Bytes read past the
+/// current request's body are stashed back into `leftover` for the next
+/// call instead of being discarded. Returns `Ok(None)` when the peer closes
+/// the connection before sending anything (a clean end of a keep-alive
+/// connection) or when it stays idle past `IDLE_TIMEOUT`.
+async fn read_request(
+    stream: &mut TcpStream,
+    leftover: &mut Vec<u8>,
+) -> anyhow::Result<Option<HttpRequest>> {
+    let mut buf = std::mem::take(leftover);
+    let mut chunk = [0u8; 1024];
+
+    let header_end = loop {
+        if let Some(pos) = find_header_terminator(&buf) {
+            break pos;
+        }
+        let read = if buf.is_empty() {
+            match timeout(IDLE_TIMEOUT, stream.read(&mut chunk)).await {
+                std::result::Result::Ok(read) => read,
+                std::result::Result::Err(_) => return Ok(None),
+            }
         } else {
-            HttpResponse::new("".to_string(), request.protocol, 404)
+            stream.read(&mut chunk).await
+        };
+        let n = read.context("CTX: handle connection read buffer")?;
+        if n == 0 {
+            if buf.is_empty() {
+                return Ok(None);
+            }
+            anyhow::bail!("connection closed before headers were complete");
         }
-    } else if request.verb == "POST" && request.path.starts_with("/files/") {
-        let filename = request.path.split_once("/files").unwrap().1;
-        dbg!("file name {}", filename);
-        let path_str = format!("{}/{}", arg.directory.to_owned().unwrap(), filename);
-        let path = Path::new(&path_str);
+        buf.extend_from_slice(&chunk[..n]);
+    };
 
-        let _ = fs::write(path, request.body);
+    let head =
+        std::str::from_utf8(&buf[..header_end]).context("CTX: request head is not valid UTF-8")?;
+    let mut lines = head.split("\r\n");
 
-        HttpResponse::new("".to_string(), request.protocol, 201)
+    let request_line = lines.next().expect("Request should contain a request line");
+    let mut parts = request_line.split_whitespace();
+    let verb = parts
+        .next()
+        .expect("Request should contains verb")
+        .to_string();
+    let path = parts
+        .next()
+        .expect("Request should contains path")
+        .to_string();
+    let protocol = parts
+        .next()
+        .expect("Request should contains protocol")
+        .to_string();
+
+    let headers = lines
+        .filter(|line| !line.is_empty())
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+        .collect::<HashMap<_, _>>();
+
+    let content_length = headers
+        .get("Content-Length")
+        .and_then(|value| value.parse::<usize>().ok())
+        .unwrap_or(0);
+
+    let mut body = buf[header_end + 4..].to_vec();
+    while body.len() < content_length {
+        let n = stream
+            .read(&mut chunk)
+            .await
+            .context("CTX: handle connection read body")?;
+        if n == 0 {
+            break;
+        }
+        body.extend_from_slice(&chunk[..n]);
+    }
+    if body.len() > content_length {
+        *leftover = body.split_off(content_length);
     } else {
-        HttpResponse::new("".to_string(), request.protocol, 404)
+        body.truncate(content_length);
+    }
+
+    Ok(Some(HttpRequest {
+        verb,
+        path,
+        protocol,
+        headers,
+        body,
+    }))
+}
+
+/// Returns the index where `\r\n\r\n` starts in `buf`, if present.
+fn find_header_terminator(buf: &[u8]) -> Option<usize> {
+    buf.windows(4).position(|window| window == b"\r\n\r\n")
+}
+
+/// Parses a single `Range: bytes=START-END` header value against a resource
+/// of `total` bytes, handling the open-ended (`bytes=START-`) and suffix
+/// (`bytes=-N`) forms. Returns the inclusive `(start, end)` byte indices, or
+/// `None` when the range cannot be satisfied.
+fn parse_range(header: &str, total: usize) -> Option<(usize, usize)> {
+    let spec = header.strip_prefix("bytes=")?;
+    let (start, end) = spec.split_once('-')?;
+
+    if start.is_empty() {
+        let suffix_len = end.parse::<usize>().ok()?;
+        if suffix_len == 0 {
+            return None;
+        }
+        let start = total.saturating_sub(suffix_len);
+        return Some((start, total.checked_sub(1)?));
+    }
+
+    let start = start.parse::<usize>().ok()?;
+    let end = if end.is_empty() {
+        total.checked_sub(1)?
+    } else {
+        end.parse::<usize>().ok()?
     };
-    stream.write_all(response.to_string().as_bytes()).await?;
-    Ok(())
+
+    if start >= total || start > end {
+        return None;
+    }
+
+    Some((start, end.min(total - 1)))
+}
+
+/// Reads just the inclusive `start..=end` byte range of the file at `path`,
+/// seeking past the unwanted prefix rather than loading the whole file.
+fn read_range(path: &Path, start: usize, end: usize) -> std::io::Result<Vec<u8>> {
+    let mut file = File::open(path)?;
+    file.seek(SeekFrom::Start(start as u64))?;
+
+    let mut slice = vec![0u8; end - start + 1];
+    file.read_exact(&mut slice)?;
+    std::result::Result::Ok(slice)
 }
 
-struct HttpResponse {
-    body: String,
+/// Guesses a `Content-Type` from `path`'s extension, falling back to
+/// `application/octet-stream` for anything unrecognized.
+fn guess_mime_type(path: &Path) -> &'static str {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("html") => "text/html",
+        Some("css") => "text/css",
+        Some("js") => "application/javascript",
+        Some("json") => "application/json",
+        Some("png") => "image/png",
+        Some("jpg") | Some("jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("txt") => "text/plain",
+        _ => "application/octet-stream",
+    }
+}
+
+pub(crate) struct HttpResponse {
+    body: Vec<u8>,
     protocol: String,
     status: String,
-    headers: HashMap<String, String>,
+    pub(crate) headers: HashMap<String, String>,
 }
 
 impl HttpResponse {
-    fn new(body: String, protocol: String, status: u16) -> Self {
+    pub(crate) fn new(body: Vec<u8>, protocol: String, status: u16) -> Self {
         let status = if status == 200 {
             "200 OK"
-        } else if status == 404 {
-            "404 Not Found"
         } else if status == 201 {
             "201 Created"
+        } else if status == 206 {
+            "206 Partial Content"
+        } else if status == 404 {
+            "404 Not Found"
+        } else if status == 416 {
+            "416 Range Not Satisfiable"
         } else {
             panic!("HTTP status code not supported")
         }
@@ -122,82 +443,163 @@ impl HttpResponse {
         self
     }
 
-    fn prepare_octet_stream_headers(mut self) -> HttpResponse {
+    fn prepare_file_headers(mut self, path: &Path) -> HttpResponse {
+        self.headers
+            .insert("Content-Type".to_string(), guess_mime_type(path).to_string());
+        self.headers
+            .insert("Content-Length".to_string(), self.body.len().to_string());
+        self.headers
+            .insert("Accept-Ranges".to_string(), "bytes".to_string());
+        self
+    }
+
+    fn prepare_range_headers(
+        mut self,
+        path: &Path,
+        start: usize,
+        end: usize,
+        total: usize,
+    ) -> HttpResponse {
+        self.headers
+            .insert("Content-Type".to_string(), guess_mime_type(path).to_string());
+        self.headers
+            .insert("Content-Length".to_string(), self.body.len().to_string());
         self.headers.insert(
-            "Content-Type".to_string(),
-            "application/octet-stream".to_string(),
+            "Content-Range".to_string(),
+            format!("bytes {}-{}/{}", start, end, total),
         );
         self.headers
-            .insert("Content-Length".to_string(), self.body.len().to_string());
+            .insert("Accept-Ranges".to_string(), "bytes".to_string());
         self
     }
-}
 
-impl fmt::Display for HttpResponse {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    fn prepare_range_not_satisfiable_headers(mut self, total: usize) -> HttpResponse {
+        self.headers
+            .insert("Content-Range".to_string(), format!("bytes */{}", total));
+        self
+    }
+
+    pub(crate) fn body(&self) -> &[u8] {
+        &self.body
+    }
+
+    pub(crate) fn set_body(&mut self, body: Vec<u8>) {
+        self.body = body;
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
         let mut response = format!("{} {}", self.protocol, self.status);
 
-        self.headers.clone().into_iter().for_each(|header| {
+        // Every response must carry its own framing so a keep-alive client
+        // knows where the body ends; fall back to the actual body length for
+        // routes that didn't already set a more specific Content-Length.
+        let mut headers = self.headers.clone();
+        headers
+            .entry("Content-Length".to_string())
+            .or_insert_with(|| self.body.len().to_string());
+
+        headers.into_iter().for_each(|header| {
             response = format!("{}\r\n{}: {}", response, header.0, header.1);
         });
+        response = format!("{}\r\n\r\n", response);
 
-        if !self.body.is_empty() {
-            response = format!("{}\r\n\r\n{}", response, self.body);
-        }
+        let mut bytes = response.into_bytes();
+        bytes.extend_from_slice(&self.body);
+        bytes
+    }
+}
 
-        write!(f, "{}\r\n\r\n", response)
+impl fmt::Display for HttpResponse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", String::from_utf8_lossy(&self.to_bytes()))
     }
 }
 
-struct HttpRequest {
-    verb: String,
-    path: String,
-    protocol: String,
-    headers: HashMap<String, String>,
-    body: String,
-}
-
-impl HttpRequest {
-    fn from_byte_array(buf: &[u8; 1024]) -> Self {
-        let data = String::from_utf8_lossy(&buf[..]);
-
-        let (parts, body) = data.split_once("\r\n\r\n").unwrap_or_default();
-        let mut parts = parts.split_whitespace();
-
-        let verb = parts
-            .next()
-            .expect("Request should contains verb")
-            .to_string();
-        let path = parts
-            .next()
-            .expect("Request should contains path")
-            .to_string();
-        let protocol = parts
-            .next()
-            .expect("Request should contains protocol")
-            .to_string();
-
-        let headers = parts
-            .collect::<Vec<_>>()
-            .chunks(2)
-            .filter(|x| x.len() == 2)
-            .map(|x| {
-                (
-                    x[0].split_once(':').unwrap().0.to_string(),
-                    x[1].to_string(),
-                )
-            })
-            .collect::<HashMap<_, _>>();
-
-        dbg!("{:#?}", headers.clone());
-        let body = body.to_string();
-        dbg!("body {:#?}", body.clone());
-        Self {
-            verb,
-            path,
-            protocol,
-            headers,
-            body,
-        }
+pub(crate) struct HttpRequest {
+    pub(crate) verb: String,
+    pub(crate) path: String,
+    pub(crate) protocol: String,
+    pub(crate) headers: HashMap<String, String>,
+    pub(crate) body: Vec<u8>,
+}
+
+#[cfg(test)]
+mod parse_range_tests {
+    use super::parse_range;
+
+    #[test]
+    fn full_range() {
+        assert_eq!(parse_range("bytes=0-9", 10), Some((0, 9)));
+    }
+
+    #[test]
+    fn open_ended_range() {
+        assert_eq!(parse_range("bytes=5-", 10), Some((5, 9)));
+    }
+
+    #[test]
+    fn suffix_range() {
+        assert_eq!(parse_range("bytes=-3", 10), Some((7, 9)));
+    }
+
+    #[test]
+    fn suffix_range_of_zero_is_unsatisfiable() {
+        assert_eq!(parse_range("bytes=-0", 10), None);
+    }
+
+    #[test]
+    fn suffix_range_larger_than_total_clamps_to_start() {
+        assert_eq!(parse_range("bytes=-100", 10), Some((0, 9)));
+    }
+
+    #[test]
+    fn end_past_total_clamps_to_last_byte() {
+        assert_eq!(parse_range("bytes=0-100", 10), Some((0, 9)));
+    }
+
+    #[test]
+    fn start_past_total_is_unsatisfiable() {
+        assert_eq!(parse_range("bytes=10-20", 10), None);
+    }
+
+    #[test]
+    fn start_after_end_is_unsatisfiable() {
+        assert_eq!(parse_range("bytes=5-2", 10), None);
+    }
+
+    #[test]
+    fn missing_bytes_prefix_is_rejected() {
+        assert_eq!(parse_range("items=0-9", 10), None);
+    }
+}
+
+#[cfg(test)]
+mod guess_mime_type_tests {
+    use super::guess_mime_type;
+    use std::path::Path;
+
+    #[test]
+    fn recognizes_known_extensions() {
+        assert_eq!(guess_mime_type(Path::new("index.html")), "text/html");
+        assert_eq!(guess_mime_type(Path::new("style.css")), "text/css");
+        assert_eq!(guess_mime_type(Path::new("app.js")), "application/javascript");
+        assert_eq!(guess_mime_type(Path::new("data.json")), "application/json");
+        assert_eq!(guess_mime_type(Path::new("photo.png")), "image/png");
+        assert_eq!(guess_mime_type(Path::new("photo.jpg")), "image/jpeg");
+        assert_eq!(guess_mime_type(Path::new("photo.jpeg")), "image/jpeg");
+        assert_eq!(guess_mime_type(Path::new("anim.gif")), "image/gif");
+        assert_eq!(guess_mime_type(Path::new("notes.txt")), "text/plain");
+    }
+
+    #[test]
+    fn falls_back_for_unknown_or_missing_extension() {
+        assert_eq!(
+            guess_mime_type(Path::new("archive.tar.gz")),
+            "application/octet-stream"
+        );
+        assert_eq!(
+            guess_mime_type(Path::new("README")),
+            "application/octet-stream"
+        );
     }
 }
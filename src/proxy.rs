@@ -0,0 +1,86 @@
+use anyhow::Context;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+
+use crate::HttpRequest;
+
+/// Forwards `request` to `upstream` over a fresh TCP connection, rewriting
+/// the `Host` header, then reads the whole upstream response into memory
+/// until the upstream closes the connection and writes it back to `client`
+/// in one piece (it is not streamed chunk by chunk), after overwriting its
+/// `Connection` header with `connection` so the client's view of persistence
+/// reflects our own keep-alive decision rather than the upstream's.
+pub(crate) async fn forward(
+    client: &mut TcpStream,
+    request: &HttpRequest,
+    upstream: &str,
+    connection: &str,
+) -> anyhow::Result<()> {
+    let mut upstream_stream = TcpStream::connect(upstream)
+        .await
+        .with_context(|| format!("CTX: connect to upstream {}", upstream))?;
+
+    let mut head = format!("{} {} {}\r\n", request.verb, request.path, request.protocol);
+    for (name, value) in &request.headers {
+        if name.eq_ignore_ascii_case("Host") {
+            continue;
+        }
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    head.push_str(&format!("Host: {}\r\n\r\n", upstream));
+
+    upstream_stream
+        .write_all(head.as_bytes())
+        .await
+        .context("CTX: write proxied request head")?;
+    upstream_stream
+        .write_all(&request.body)
+        .await
+        .context("CTX: write proxied request body")?;
+
+    let mut response = Vec::new();
+    let mut chunk = [0u8; 4096];
+    loop {
+        let n = upstream_stream
+            .read(&mut chunk)
+            .await
+            .context("CTX: read proxied response")?;
+        if n == 0 {
+            break;
+        }
+        response.extend_from_slice(&chunk[..n]);
+    }
+
+    let response = set_connection_header(response, connection);
+
+    client
+        .write_all(&response)
+        .await
+        .context("CTX: write proxied response to client")?;
+    Ok(())
+}
+
+/// Replaces (or appends) the `Connection` header in a raw HTTP response's
+/// head with `connection`, leaving the rest of the head and the body as-is.
+fn set_connection_header(response: Vec<u8>, connection: &str) -> Vec<u8> {
+    let Some(head_end) = response.windows(4).position(|window| window == b"\r\n\r\n") else {
+        return response;
+    };
+
+    let Ok(head) = std::str::from_utf8(&response[..head_end]) else {
+        return response;
+    };
+
+    let mut lines: Vec<&str> = head
+        .split("\r\n")
+        .filter(|line| !line.split_once(':').is_some_and(|(name, _)| name.eq_ignore_ascii_case("Connection")))
+        .collect();
+    let connection_line = format!("Connection: {}", connection);
+    lines.push(&connection_line);
+
+    let mut rewritten = lines.join("\r\n").into_bytes();
+    rewritten.extend_from_slice(&response[head_end..]);
+    rewritten
+}
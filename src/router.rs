@@ -0,0 +1,133 @@
+use std::collections::HashMap;
+
+use crate::{HttpRequest, HttpResponse};
+
+/// HTTP methods a route can be registered against.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Method {
+    Get,
+    Post,
+}
+
+impl Method {
+    fn as_str(self) -> &'static str {
+        match self {
+            Method::Get => "GET",
+            Method::Post => "POST",
+        }
+    }
+}
+
+/// A route handler: given the parsed request, its captured path params, and
+/// shared context, produces a response.
+pub(crate) type Handler<C> =
+    Box<dyn Fn(&HttpRequest, &HashMap<String, String>, &C) -> HttpResponse + Send + Sync>;
+
+/// A minimal path router. Patterns are matched segment-by-segment against
+/// the request path; a segment written as `:name` captures that segment
+/// into the params map handed to the handler, e.g. `/echo/:text` matches
+/// `/echo/hello` with `{"text": "hello"}`. A trailing `:name*` segment
+/// instead captures every remaining segment, slashes included, e.g.
+/// `/files/:name*` matches `/files/sub/dir.txt` with `{"name": "sub/dir.txt"}`.
+pub(crate) struct Router<C> {
+    routes: Vec<(Method, String, Handler<C>)>,
+}
+
+impl<C> Router<C> {
+    pub(crate) fn new() -> Self {
+        Self { routes: Vec::new() }
+    }
+
+    pub(crate) fn register(&mut self, method: Method, pattern: &str, handler: Handler<C>) {
+        self.routes.push((method, pattern.to_string(), handler));
+    }
+
+    /// Dispatches `request` to the first registered route whose method and
+    /// pattern match, falling back to a plain 404 when nothing matches.
+    pub(crate) fn dispatch(&self, request: &HttpRequest, ctx: &C) -> HttpResponse {
+        for (method, pattern, handler) in &self.routes {
+            if method.as_str() == request.verb {
+                if let Some(params) = match_path(pattern, &request.path) {
+                    return handler(request, &params, ctx);
+                }
+            }
+        }
+        HttpResponse::new(Vec::new(), request.protocol.clone(), 404)
+    }
+}
+
+/// Matches `path` against `pattern` segment-by-segment, returning captured
+/// `:name`/`:name*` params on success.
+fn match_path(pattern: &str, path: &str) -> Option<HashMap<String, String>> {
+    let pattern_segments: Vec<&str> = pattern.split('/').collect();
+    let path_segments: Vec<&str> = path.split('/').collect();
+    let mut params = HashMap::new();
+
+    for (index, pattern_segment) in pattern_segments.iter().enumerate() {
+        if let Some(name) = pattern_segment.strip_prefix(':').and_then(|n| n.strip_suffix('*')) {
+            let rest = path_segments.get(index..)?;
+            if rest.is_empty() {
+                return None;
+            }
+            params.insert(name.to_string(), rest.join("/"));
+            return Some(params);
+        }
+
+        let path_segment = path_segments.get(index)?;
+        if let Some(name) = pattern_segment.strip_prefix(':') {
+            params.insert(name.to_string(), path_segment.to_string());
+        } else if *pattern_segment != *path_segment {
+            return None;
+        }
+    }
+
+    if path_segments.len() > pattern_segments.len() {
+        return None;
+    }
+
+    Some(params)
+}
+
+#[cfg(test)]
+mod match_path_tests {
+    use super::match_path;
+
+    #[test]
+    fn matches_literal_path() {
+        let params = match_path("/user-agent", "/user-agent").unwrap();
+        assert!(params.is_empty());
+    }
+
+    #[test]
+    fn captures_single_named_segment() {
+        let params = match_path("/echo/:text", "/echo/hello").unwrap();
+        assert_eq!(params.get("text").unwrap(), "hello");
+    }
+
+    #[test]
+    fn rejects_extra_segments_without_a_catch_all() {
+        assert!(match_path("/echo/:text", "/echo/hello/world").is_none());
+    }
+
+    #[test]
+    fn catch_all_captures_remaining_segments_with_slashes() {
+        let params = match_path("/files/:name*", "/files/sub/dir.txt").unwrap();
+        assert_eq!(params.get("name").unwrap(), "sub/dir.txt");
+    }
+
+    #[test]
+    fn catch_all_captures_single_segment_too() {
+        let params = match_path("/files/:name*", "/files/dir.txt").unwrap();
+        assert_eq!(params.get("name").unwrap(), "dir.txt");
+    }
+
+    #[test]
+    fn catch_all_requires_at_least_one_segment() {
+        assert!(match_path("/files/:name*", "/files").is_none());
+    }
+
+    #[test]
+    fn mismatched_literal_segment_fails() {
+        assert!(match_path("/user-agent", "/files").is_none());
+    }
+}